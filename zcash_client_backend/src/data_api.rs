@@ -1,6 +1,8 @@
 use std::cmp;
 use std::collections::HashMap;
 use std::fmt::Debug;
+use std::num::NonZeroU32;
+use std::ops::Range;
 
 use zcash_primitives::{
     block::BlockHash,
@@ -10,12 +12,11 @@ use zcash_primitives::{
     primitives::{Note, Nullifier, PaymentAddress},
     sapling::Node,
     transaction::{components::Amount, Transaction, TxId},
-    zip32::ExtendedFullViewingKey,
+    zip32::{DiversifierIndex, ExtendedFullViewingKey},
 };
 
 use crate::{
     address::RecipientAddress,
-    data_api::wallet::ANCHOR_OFFSET,
     decrypt::DecryptedOutput,
     proto::compact_formats::CompactBlock,
     wallet::{AccountId, SpendableNote, WalletShieldedOutput, WalletTx},
@@ -25,6 +26,194 @@ pub mod chain;
 pub mod error;
 pub mod wallet;
 
+/// A Unified Address, as described in ZIP 316.
+///
+/// A unified address bundles together the receivers for one or more shielded or
+/// transparent pools derived from a single [`UnifiedFullViewingKey`], so that a wallet
+/// can hand out a single address that a sender can use regardless of which pool(s) it
+/// supports. Only the Sapling receiver is currently represented; transparent and
+/// Orchard receivers can be added to this struct without changing its public API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnifiedAddress {
+    sapling: PaymentAddress,
+}
+
+impl UnifiedAddress {
+    /// Constructs a unified address from its Sapling receiver.
+    pub fn from_sapling(sapling: PaymentAddress) -> Self {
+        UnifiedAddress { sapling }
+    }
+
+    /// Returns the Sapling receiver of this unified address.
+    pub fn sapling(&self) -> &PaymentAddress {
+        &self.sapling
+    }
+}
+
+/// A Unified Full Viewing Key, as described in ZIP 316.
+///
+/// A UFVK bundles together the full viewing keys for one or more shielded or
+/// transparent pools, giving a wallet a single, pool-agnostic identity for an account.
+/// Only the Sapling FVK is currently represented; transparent and Orchard component
+/// keys can be added to this struct without changing its public API.
+///
+/// `PartialEq`/`Eq` let a backend compare an incoming UFVK against the ones it has
+/// stored (see [`WalletRead::get_account_for_ufvk`]) without needing to serialize
+/// either side first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnifiedFullViewingKey {
+    sapling: ExtendedFullViewingKey,
+}
+
+impl UnifiedFullViewingKey {
+    /// Constructs a unified full viewing key from its Sapling component.
+    pub fn new(sapling: ExtendedFullViewingKey) -> Self {
+        UnifiedFullViewingKey { sapling }
+    }
+
+    /// Returns the Sapling extended full viewing key component of this UFVK.
+    pub fn sapling(&self) -> &ExtendedFullViewingKey {
+        &self.sapling
+    }
+
+    /// Derives the unified address for the first diversifier index at or after `j`
+    /// that produces a valid Sapling diversifier. The index actually used is not
+    /// returned. Returns `None` if no such index exists.
+    pub fn address(&self, j: DiversifierIndex) -> Option<UnifiedAddress> {
+        self.sapling
+            .address(j)
+            .ok()
+            .map(|(_, addr)| UnifiedAddress::from_sapling(addr))
+    }
+}
+
+/// The priority that the wallet backend assigns to a range of blocks that have not yet
+/// been scanned, used to order the results of [`WalletRead::suggest_scan_ranges`].
+///
+/// Variants are ordered from least to most urgent, so that e.g. `ScanPriority::Verify >
+/// ScanPriority::Historic` holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ScanPriority {
+    /// A range that has been explicitly excluded from scanning, e.g. because it lies
+    /// entirely below an account's birthday height.
+    Ignored,
+    /// A range that has already been scanned.
+    Scanned,
+    /// A range with no particular urgency; typically, chain history below the point at
+    /// which the wallet was created.
+    Historic,
+    /// A range immediately adjacent to a range that has already been scanned, and thus
+    /// cheap to extend outward from.
+    OpenAdjacent,
+    /// A range in which a note was detected but the surrounding context needed to
+    /// construct a witness for it is missing.
+    FoundNote,
+    /// A range at or near the current chain tip, scanned first so that the wallet can
+    /// show an up-to-date balance as quickly as possible.
+    ChainTip,
+    /// A small range below the last scanned tip that is rescanned on receipt of new
+    /// blocks, in order to detect chain reorgs.
+    Verify,
+}
+
+/// A range of block heights that the wallet backend has determined should be scanned,
+/// tagged with the urgency of scanning it. Returned by
+/// [`WalletRead::suggest_scan_ranges`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanRange {
+    block_range: Range<BlockHeight>,
+    priority: ScanPriority,
+}
+
+impl ScanRange {
+    /// Constructs a scan range from its constituent parts.
+    pub fn from_parts(block_range: Range<BlockHeight>, priority: ScanPriority) -> Self {
+        ScanRange {
+            block_range,
+            priority,
+        }
+    }
+
+    /// Returns the range of block heights to be scanned.
+    pub fn block_range(&self) -> &Range<BlockHeight> {
+        &self.block_range
+    }
+
+    /// Returns the priority the backend has assigned to this range.
+    pub fn priority(&self) -> ScanPriority {
+        self.priority
+    }
+}
+
+/// Metadata describing a block that the wallet has scanned, sufficient to resume
+/// scanning or witness construction from that point without needing the blocks below
+/// it to have been scanned first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockMetadata {
+    block_height: BlockHeight,
+    block_hash: BlockHash,
+    sapling_commitment_tree_size: u32,
+}
+
+impl BlockMetadata {
+    /// Constructs a `BlockMetadata` value from its constituent parts.
+    pub fn from_parts(
+        block_height: BlockHeight,
+        block_hash: BlockHash,
+        sapling_commitment_tree_size: u32,
+    ) -> Self {
+        BlockMetadata {
+            block_height,
+            block_hash,
+            sapling_commitment_tree_size,
+        }
+    }
+
+    /// Returns the height of the block this metadata describes.
+    pub fn block_height(&self) -> BlockHeight {
+        self.block_height
+    }
+
+    /// Returns the hash of the block this metadata describes.
+    pub fn block_hash(&self) -> BlockHash {
+        self.block_hash
+    }
+
+    /// Returns the size of the Sapling note commitment tree as of the end of the block
+    /// this metadata describes.
+    pub fn sapling_commitment_tree_size(&self) -> u32 {
+        self.sapling_commitment_tree_size
+    }
+}
+
+/// Computes the target and anchor heights given the range of block heights known to
+/// the backend. The anchor height is clamped so that it falls below neither the
+/// earliest known block nor the wallet's birthday, if any; the target height is always
+/// `max_height + 1`.
+///
+/// Factored out of [`WalletRead::get_target_and_anchor_heights`] so that the clamping
+/// logic can be unit tested without a backend.
+fn target_and_anchor_heights(
+    min_height: BlockHeight,
+    max_height: BlockHeight,
+    birthday: Option<BlockHeight>,
+    min_confirmations: NonZeroU32,
+) -> (BlockHeight, BlockHeight) {
+    // Never suggest an anchor below the wallet's birthday: restoring from seed
+    // should not force scanning the entire chain from Sapling activation.
+    let min_height = birthday.map_or(min_height, |b| cmp::max(min_height, b));
+    let target_height = max_height + 1;
+
+    // Select an anchor min_confirmations back from the target block, unless
+    // that would be before the earliest block we have.
+    let anchor_height = BlockHeight::from(cmp::max(
+        u32::from(target_height).saturating_sub(u32::from(min_confirmations)),
+        u32::from(min_height),
+    ));
+
+    (target_height, anchor_height)
+}
+
 /// Read-only operations require for light wallet functions.
 ///
 /// This trait defines the read-only portion of the storage
@@ -39,37 +228,69 @@ pub trait WalletRead {
     ///
     /// For example, this might be a database identifier type
     /// or a UUID.
-    type NoteRef: Copy + Debug;
+    ///
+    /// `Eq + Ord` let callers store note references in a `BTreeMap`/`BTreeSet` and
+    /// dedup or sort them deterministically, which [`WalletRead::select_spendable_notes`]
+    /// relies on to return a stable, reproducible selection of notes. Backends should
+    /// choose a `NoteRef` representation (e.g. an auto-incrementing row id) whose
+    /// ordering reflects note insertion order, so that the "oldest-first" ordering
+    /// `select_spendable_notes` promises is meaningful rather than arbitrary.
+    type NoteRef: Copy + Debug + Eq + Ord;
 
     /// Backend-specific transaction identifier.
     ///
     /// For example, this might be a database identifier type
     /// or a TxId if the backend is able to support that type
     /// directly.
-    type TxRef: Copy + Debug;
+    ///
+    /// `Eq + Ord` let callers store transaction references in a
+    /// `BTreeMap`/`BTreeSet` and dedup or sort them deterministically.
+    type TxRef: Copy + Debug + Eq + Ord;
 
     /// Returns the minimum and maximum block heights for stored blocks.
     fn block_height_extrema(&self) -> Result<Option<(BlockHeight, BlockHeight)>, Self::Error>;
 
-    /// Returns the default target height and anchor height, given the
-    /// range of block heights that the backend knows about.
+    /// Returns the height below which the wallet will never scan, regardless of the
+    /// heights of any accounts it holds. This is the minimum of the birthday heights of
+    /// all the accounts the wallet is tracking, as reported by
+    /// [`WalletRead::get_account_birthday`].
+    ///
+    /// Backends that do not persist birthdays may rely on this default, which imposes
+    /// no floor at all; callers then fall back to the pre-birthday behavior of scanning
+    /// from the earliest block the backend knows about.
+    fn get_wallet_birthday(&self) -> Result<Option<BlockHeight>, Self::Error> {
+        Ok(None)
+    }
+
+    /// Returns the height at which the specified account's keys were created, below
+    /// which the wallet does not need to scan on that account's behalf.
+    ///
+    /// Backends that do not persist birthdays may rely on this default, which reports
+    /// block height zero and so imposes no floor on scanning for any account.
+    fn get_account_birthday(&self, _account: AccountId) -> Result<BlockHeight, Self::Error> {
+        Ok(BlockHeight::from(0))
+    }
+
+    /// Returns the default target height and anchor height, given the range of block
+    /// heights that the backend knows about.
+    ///
+    /// `min_confirmations` is the confirmation depth the caller requires of the
+    /// anchor: an anchor height of `target_height - min_confirmations` is selected,
+    /// clamped to the earliest block we have. Using `NonZeroU32` statically rules out a
+    /// zero-confirmation anchor, which would select the unmined target block itself.
+    /// Different callers can pass different values here to trade off spendability
+    /// against confirmation depth, rather than being stuck with a single hardcoded
+    /// policy.
     fn get_target_and_anchor_heights(
         &self,
+        min_confirmations: NonZeroU32,
     ) -> Result<Option<(BlockHeight, BlockHeight)>, Self::Error> {
-        self.block_height_extrema().map(|heights| {
-            heights.map(|(min_height, max_height)| {
-                let target_height = max_height + 1;
-
-                // Select an anchor ANCHOR_OFFSET back from the target block,
-                // unless that would be before the earliest block we have.
-                let anchor_height = BlockHeight::from(cmp::max(
-                    u32::from(target_height).saturating_sub(ANCHOR_OFFSET),
-                    u32::from(min_height),
-                ));
-
-                (target_height, anchor_height)
-            })
-        })
+        let extrema = self.block_height_extrema()?;
+        let birthday = self.get_wallet_birthday()?;
+
+        Ok(extrema.map(|(min_height, max_height)| {
+            target_and_anchor_heights(min_height, max_height, birthday, min_confirmations)
+        }))
     }
 
     /// Returns the block hash for the block at the given height
@@ -93,6 +314,19 @@ pub trait WalletRead {
     /// Returns the block height in which the specified transaction was mined.
     fn get_tx_height(&self, txid: TxId) -> Result<Option<BlockHeight>, Self::Error>;
 
+    /// Returns the full decoded transaction previously stored via
+    /// [`WalletWrite::put_tx_data`], deserialized at the consensus branch active at its
+    /// mined height (or, if it is not yet mined, the most current branch known to the
+    /// wallet), or `None` if no raw transaction was stored for this reference. This
+    /// allows callers to re-display outgoing transaction details, rebroadcast a
+    /// transaction, or inspect fees after the fact.
+    ///
+    /// Defaults to `Ok(None)` so that backends which do not yet persist raw transaction
+    /// bytes are not required to implement this method.
+    fn get_transaction(&self, _id_tx: Self::TxRef) -> Result<Option<Transaction>, Self::Error> {
+        Ok(None)
+    }
+
     /// Returns the payment address for the specified account, if the account
     /// identifier specified refers to a valid account for this wallet.
     fn get_address<P: consensus::Parameters>(
@@ -108,7 +342,7 @@ pub trait WalletRead {
         params: &P,
     ) -> Result<HashMap<AccountId, ExtendedFullViewingKey>, Self::Error>;
 
-    /// Checks whether the specified extended full viewing key is 
+    /// Checks whether the specified extended full viewing key is
     /// associated with the account.
     fn is_valid_account_extfvk<P: consensus::Parameters>(
         &self,
@@ -117,6 +351,47 @@ pub trait WalletRead {
         extfvk: &ExtendedFullViewingKey,
     ) -> Result<bool, Self::Error>;
 
+    /// Returns the most recently generated unified address for the specified account,
+    /// if the account identifier specified refers to a valid account for this wallet.
+    ///
+    /// Defaults to `Ok(None)` for backends that have not yet migrated off a single
+    /// per-account [`WalletRead::get_address`].
+    fn get_current_address(
+        &self,
+        _account: AccountId,
+    ) -> Result<Option<UnifiedAddress>, Self::Error> {
+        Ok(None)
+    }
+
+    /// Returns all unified full viewing keys known about by this wallet, keyed by the
+    /// pool-agnostic account identity they were derived for.
+    ///
+    /// Defaults to an empty map for backends that have not yet migrated off
+    /// [`WalletRead::get_extended_full_viewing_keys`].
+    fn get_unified_full_viewing_keys(
+        &self,
+    ) -> Result<HashMap<AccountId, UnifiedFullViewingKey>, Self::Error> {
+        Ok(HashMap::new())
+    }
+
+    /// Returns the account identifier corresponding to a given unified full viewing
+    /// key, if that UFVK is known to this wallet.
+    ///
+    /// The default implementation is derived from
+    /// [`WalletRead::get_unified_full_viewing_keys`] by linear search; backends that
+    /// can look accounts up by UFVK more directly (e.g. via an indexed column) should
+    /// override this.
+    fn get_account_for_ufvk(
+        &self,
+        ufvk: &UnifiedFullViewingKey,
+    ) -> Result<Option<AccountId>, Self::Error> {
+        Ok(self
+            .get_unified_full_viewing_keys()?
+            .into_iter()
+            .find(|(_, candidate)| candidate == ufvk)
+            .map(|(account, _)| account))
+    }
+
     /// Returns the wallet balance for the specified account.
     ///
     /// This balance amount is the raw balance of all transactions in known
@@ -136,6 +411,21 @@ pub trait WalletRead {
         anchor_height: BlockHeight,
     ) -> Result<Amount, Self::Error>;
 
+    /// Returns the full decoded memo for a note, if it is known.
+    ///
+    /// Unlike [`WalletRead::get_received_memo_as_utf8`] and
+    /// [`WalletRead::get_sent_memo_as_utf8`], this does not require the memo to be
+    /// valid UTF-8, so callers can decode ZIP 302-style structured or binary memos
+    /// themselves, and can distinguish an explicitly empty memo from one that simply
+    /// happens to be an empty string. Implementations of the UTF-8 helpers below
+    /// should be thin wrappers over this method.
+    ///
+    /// Defaults to `Ok(None)` for backends that do not yet persist the raw memo bytes
+    /// alongside a note.
+    fn get_memo(&self, _id_note: Self::NoteRef) -> Result<Option<Memo>, Self::Error> {
+        Ok(None)
+    }
+
     /// Returns the memo for a received note, if it is known and a valid UTF-8 string.
     fn get_received_memo_as_utf8(
         &self,
@@ -145,6 +435,60 @@ pub trait WalletRead {
     /// Returns the memo for a sent note, if it is known and a valid UTF-8 string.
     fn get_sent_memo_as_utf8(&self, id_note: Self::NoteRef) -> Result<Option<String>, Self::Error>;
 
+    /// Returns the height of the block at the current chain tip known to the wallet,
+    /// if any.
+    ///
+    /// The default is derived from [`WalletRead::block_height_extrema`], which is
+    /// correct as long as the backend scans contiguously from its earliest known
+    /// block; backends that support non-contiguous scanning and track the chain tip
+    /// independently of what has been scanned should override this.
+    fn chain_height(&self) -> Result<Option<BlockHeight>, Self::Error> {
+        Ok(self.block_height_extrema()?.map(|(_, max_height)| max_height))
+    }
+
+    /// Returns the metadata for the block at the given height, if the wallet has
+    /// scanned it.
+    ///
+    /// Defaults to `Ok(None)` for backends that do not yet persist per-block
+    /// commitment-tree-size metadata.
+    fn block_metadata(&self, _height: BlockHeight) -> Result<Option<BlockMetadata>, Self::Error> {
+        Ok(None)
+    }
+
+    /// Returns the metadata for the highest block `h` such that every block at a
+    /// height `<= h` has been scanned, even if higher blocks have also been scanned.
+    ///
+    /// Defaults to `Ok(None)`, which is always a safe (if maximally conservative)
+    /// answer for a backend that does not track non-contiguous scan ranges.
+    fn block_fully_scanned(&self) -> Result<Option<BlockMetadata>, Self::Error> {
+        Ok(None)
+    }
+
+    /// Returns the metadata for the highest block that has been scanned, even if gaps
+    /// exist in the scanned ranges below it.
+    ///
+    /// Defaults to `Ok(None)`, which is always a safe (if maximally conservative)
+    /// answer for a backend that does not track non-contiguous scan ranges.
+    fn block_max_scanned(&self) -> Result<Option<BlockMetadata>, Self::Error> {
+        Ok(None)
+    }
+
+    /// Returns the set of block ranges that have not yet been scanned, ordered from
+    /// highest to lowest [`ScanPriority`], with contiguous ranges of the same priority
+    /// collapsed into a single entry.
+    ///
+    /// This allows a wallet to scan out of order: new chain-tip blocks and the small
+    /// range below the last scanned tip (rescanned to catch reorgs) are suggested
+    /// first, so that a balance can be shown quickly, with historic block ranges
+    /// backfilled afterwards.
+    ///
+    /// Defaults to an empty list, which is always safe (if maximally conservative) for
+    /// a backend that has not yet implemented range tracking; such a backend should
+    /// continue to scan linearly via [`BlockSource::with_blocks`] in the meantime.
+    fn suggest_scan_ranges(&self) -> Result<Vec<ScanRange>, Self::Error> {
+        Ok(Vec::new())
+    }
+
     /// Returns the note commitment tree at the specified block height.
     fn get_commitment_tree(
         &self,
@@ -161,14 +505,37 @@ pub trait WalletRead {
     /// with which they are associated.
     fn get_nullifiers(&self) -> Result<Vec<(Nullifier, AccountId)>, Self::Error>;
 
+    /// Returns a list of spendable notes, together with their note references,
+    /// sufficient to cover the specified target value, if possible. The notes may be
+    /// returned in any order; [`WalletRead::select_spendable_notes`] is responsible for
+    /// imposing a deterministic order on top of this.
+    ///
+    /// `account` identifies the spending account by its pool-agnostic [`AccountId`], so
+    /// notes received at any of that account's receivers are eligible for selection
+    /// together.
+    fn select_unordered_spendable_notes(
+        &self,
+        account: AccountId,
+        target_value: Amount,
+        anchor_height: BlockHeight,
+    ) -> Result<Vec<(Self::NoteRef, SpendableNote)>, Self::Error>;
+
     /// Returns a list of spendable notes sufficient to cover the specified
     /// target value, if possible.
+    ///
+    /// This is a thin wrapper over [`WalletRead::select_unordered_spendable_notes`]
+    /// that sorts the result oldest-first by note reference, so that coin selection is
+    /// stable and reproducible across calls and across backends.
     fn select_spendable_notes(
         &self,
         account: AccountId,
         target_value: Amount,
         anchor_height: BlockHeight,
-    ) -> Result<Vec<SpendableNote>, Self::Error>;
+    ) -> Result<Vec<SpendableNote>, Self::Error> {
+        let mut notes = self.select_unordered_spendable_notes(account, target_value, anchor_height)?;
+        notes.sort_by_key(|(note_ref, _)| *note_ref);
+        Ok(notes.into_iter().map(|(_, note)| note).collect())
+    }
 }
 
 /// This trait encapsulate the write capabilities required to update stored
@@ -183,7 +550,28 @@ pub trait WalletWrite: WalletRead {
     where
         F: FnOnce(&mut Self) -> Result<A, Self::Error>;
 
+    /// Records the height at which the specified account's keys were created. This
+    /// should be called once, when the account is first imported, so that scanning can
+    /// be bounded below by [`WalletRead::get_account_birthday`] instead of starting
+    /// from Sapling activation.
+    ///
+    /// Defaults to a no-op for backends that do not yet persist per-account birthdays;
+    /// such backends will continue to report a birthday floor of block height zero from
+    /// [`WalletRead::get_account_birthday`] regardless of this call.
+    fn set_account_birthday(
+        &mut self,
+        _account: AccountId,
+        _birthday: BlockHeight,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
     /// Add the data for a block to the data store.
+    ///
+    /// The size of the Sapling note commitment tree as of the end of this block is
+    /// derived from `commitment_tree` itself, so that a witness for a note discovered
+    /// while scanning a later, non-contiguous range can be constructed from the nearest
+    /// prior checkpoint without having scanned everything in between.
     fn insert_block(
         &mut self,
         block_height: BlockHeight,
@@ -248,6 +636,11 @@ pub trait WalletWrite: WalletRead {
         tx_ref: Self::TxRef,
     ) -> Result<(), Self::Error>;
 
+    /// `account` identifies the sending account by its pool-agnostic [`AccountId`], the
+    /// same identity keyed by [`WalletRead::get_unified_full_viewing_keys`], rather
+    /// than by any single pool's viewing key; this is what allows a sent note to be
+    /// recorded against an account regardless of which of that account's receivers
+    /// (Sapling today, transparent/Orchard in the future) the recipient used.
     fn insert_sent_note<P: consensus::Parameters>(
         &mut self,
         params: &P,
@@ -325,3 +718,47 @@ impl ShieldedOutput for DecryptedOutput {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use zcash_primitives::consensus::BlockHeight;
+
+    use super::target_and_anchor_heights;
+
+    #[test]
+    fn target_and_anchor_heights_clamps_to_birthday() {
+        // Without a birthday, the anchor is free to go all the way down to the
+        // earliest known block.
+        let (target, anchor) = target_and_anchor_heights(
+            BlockHeight::from(1),
+            BlockHeight::from(100),
+            None,
+            NonZeroU32::new(10).unwrap(),
+        );
+        assert_eq!(target, BlockHeight::from(101));
+        assert_eq!(anchor, BlockHeight::from(91));
+
+        // A birthday above the naive min_confirmations-back anchor raises the floor.
+        let (target, anchor) = target_and_anchor_heights(
+            BlockHeight::from(1),
+            BlockHeight::from(100),
+            Some(BlockHeight::from(95)),
+            NonZeroU32::new(10).unwrap(),
+        );
+        assert_eq!(target, BlockHeight::from(101));
+        assert_eq!(anchor, BlockHeight::from(95));
+
+        // A birthday below the earliest known block has no effect, since the stored
+        // min_height is already the tighter bound.
+        let (target, anchor) = target_and_anchor_heights(
+            BlockHeight::from(50),
+            BlockHeight::from(100),
+            Some(BlockHeight::from(1)),
+            NonZeroU32::new(10).unwrap(),
+        );
+        assert_eq!(target, BlockHeight::from(101));
+        assert_eq!(anchor, BlockHeight::from(91));
+    }
+}
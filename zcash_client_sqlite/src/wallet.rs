@@ -11,13 +11,14 @@ use zcash_primitives::{
     primitives::{Nullifier, PaymentAddress},
     sapling::Node,
     transaction::{components::Amount, TxId},
-    zip32::ExtendedFullViewingKey,
+    zip32::{DiversifierIndex, ExtendedFullViewingKey},
 };
 
 use zcash_client_backend::{
     data_api::error::Error,
     encoding::{
         decode_extended_full_viewing_key, decode_payment_address, encode_extended_full_viewing_key,
+        encode_payment_address,
     },
 };
 
@@ -115,6 +116,185 @@ pub fn is_valid_account_extfvk<P: consensus::Parameters>(
         .map_err(SqliteClientError::from)
 }
 
+/// Ensures the `addresses` table backing [`get_addresses`] and
+/// [`get_next_available_address`] exists.
+///
+/// `wallet::init` does not create this table; it is created here, idempotently, the
+/// first time either function is called, so that neither fails with "no such table:
+/// addresses".
+fn ensure_addresses_table(data: &WalletDB) -> Result<(), SqliteClientError> {
+    data.0.execute(
+        "CREATE TABLE IF NOT EXISTS addresses (
+            account INTEGER NOT NULL,
+            diversifier_index_be BLOB NOT NULL,
+            address TEXT NOT NULL,
+            PRIMARY KEY (account, diversifier_index_be)
+        )",
+        NO_PARAMS,
+    )?;
+
+    Ok(())
+}
+
+/// Returns every diversified address the wallet has generated for the account, paired
+/// with the diversifier index it was derived from, ordered by diversifier index.
+///
+/// Note-scanning and balance queries do not depend on which of these addresses
+/// received a given payment, as notes are tracked by nullifier rather than by address.
+///
+/// # Examples
+///
+/// ```
+/// use tempfile::NamedTempFile;
+/// use zcash_primitives::consensus::Network;
+/// use zcash_client_backend::wallet::AccountId;
+/// use zcash_client_sqlite::{
+///     WalletDB,
+///     wallet::get_addresses,
+/// };
+///
+/// let data_file = NamedTempFile::new().unwrap();
+/// let db = WalletDB::for_path(data_file).unwrap();
+/// let addrs = get_addresses(&db, &Network::TestNetwork, AccountId(0));
+/// ```
+pub fn get_addresses<P: consensus::Parameters>(
+    data: &WalletDB,
+    params: &P,
+    account: AccountId,
+) -> Result<Vec<(DiversifierIndex, PaymentAddress)>, SqliteClientError> {
+    ensure_addresses_table(data)?;
+
+    let mut stmt_fetch_addresses = data.0.prepare(
+        "SELECT diversifier_index_be, address FROM addresses
+        WHERE account = ?
+        ORDER BY diversifier_index_be ASC",
+    )?;
+
+    let rows = stmt_fetch_addresses.query_map(&[account.0], |row| {
+        let di_be: Vec<u8> = row.get(0)?;
+        let addr_str: String = row.get(1)?;
+        Ok((di_be, addr_str))
+    })?;
+
+    let mut res = vec![];
+    for row in rows {
+        let (di_be, addr_str) = row?;
+
+        let mut di_bytes = [0u8; 11];
+        di_bytes.copy_from_slice(&di_be);
+        di_bytes.reverse();
+
+        // The stored address has already been validated at insertion time, so any
+        // decoding failure here indicates database corruption.
+        let addr = decode_payment_address(params.hrp_sapling_payment_address(), &addr_str)
+            .map_err(|e| SqliteClientError(e.into()))?
+            .ok_or_else(|| {
+                SqliteClientError(Error::CorruptedData(
+                    "Stored address has the wrong HRP for this network".to_string(),
+                ))
+            })?;
+
+        res.push((DiversifierIndex(di_bytes), addr));
+    }
+
+    Ok(res)
+}
+
+/// Derives, persists, and returns the next diversified address for the account that has
+/// not already been handed out, starting the search from the diversifier index one past
+/// the most recently stored address (or zero, if none has been stored yet).
+///
+/// Returns `None` if the account is not known to this wallet.
+///
+/// # Examples
+///
+/// ```
+/// use tempfile::NamedTempFile;
+/// use zcash_primitives::consensus::Network;
+/// use zcash_client_backend::wallet::AccountId;
+/// use zcash_client_sqlite::{
+///     WalletDB,
+///     wallet::get_next_available_address,
+/// };
+///
+/// let data_file = NamedTempFile::new().unwrap();
+/// let db = WalletDB::for_path(data_file).unwrap();
+/// let addr = get_next_available_address(&db, &Network::TestNetwork, AccountId(0));
+/// ```
+pub fn get_next_available_address<P: consensus::Parameters>(
+    data: &WalletDB,
+    params: &P,
+    account: AccountId,
+) -> Result<Option<PaymentAddress>, SqliteClientError> {
+    ensure_addresses_table(data)?;
+
+    let extfvks = get_extended_full_viewing_keys(data, params)?;
+    let extfvk = match extfvks.get(&account) {
+        Some(extfvk) => extfvk,
+        None => return Ok(None),
+    };
+
+    let last_index: Option<DiversifierIndex> = data
+        .0
+        .query_row(
+            "SELECT diversifier_index_be FROM addresses
+            WHERE account = ?
+            ORDER BY diversifier_index_be DESC
+            LIMIT 1",
+            &[account.0],
+            |row| {
+                let di_be: Vec<u8> = row.get(0)?;
+                let mut di_bytes = [0u8; 11];
+                di_bytes.copy_from_slice(&di_be);
+                di_bytes.reverse();
+                Ok(DiversifierIndex(di_bytes))
+            },
+        )
+        .optional()?;
+
+    let mut next_index = match last_index {
+        Some(mut di) => {
+            di.increment()
+                .map_err(|_| SqliteClientError(Error::CorruptedData(
+                    "Diversifier index space for this account is exhausted".to_string(),
+                )))?;
+            di
+        }
+        None => DiversifierIndex::new(),
+    };
+
+    loop {
+        match extfvk.address(next_index) {
+            Ok((valid_index, addr)) => {
+                let addr_str =
+                    encode_payment_address(params.hrp_sapling_payment_address(), &addr);
+
+                let mut di_be = valid_index.0;
+                di_be.reverse();
+
+                data.0.execute(
+                    "INSERT INTO addresses (account, diversifier_index_be, address)
+                    VALUES (?, ?, ?)",
+                    &[
+                        account.0.to_sql()?,
+                        di_be.to_vec().to_sql()?,
+                        addr_str.to_sql()?,
+                    ],
+                )?;
+
+                return Ok(Some(addr));
+            }
+            Err(()) => {
+                next_index.increment().map_err(|_| {
+                    SqliteClientError(Error::CorruptedData(
+                        "Diversifier index space for this account is exhausted".to_string(),
+                    ))
+                })?;
+            }
+        }
+    }
+}
+
 /// Returns the balance for the account, including all mined unspent notes that we know
 /// about.
 ///
@@ -194,10 +374,37 @@ pub fn get_verified_balance(
     }
 }
 
-/// Returns the memo for a received note, if it is known and a valid UTF-8 string.
+/// A coarse classification of a decoded [`Memo`], distinguishing the cases a caller is
+/// typically interested in without requiring them to inspect the raw bytes themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoKind {
+    /// The memo was the all-zeroes-with-leading-`0xF6` sentinel, i.e. no memo was set.
+    Empty,
+    /// The memo decodes as a valid UTF-8 string.
+    Text,
+    /// The memo is present but is not valid UTF-8 text (for example, a ZIP 302
+    /// structured memo or other binary payload).
+    Binary,
+}
+
+/// Returns the coarse classification of a decoded memo; see [`MemoKind`].
+pub fn classify_memo(memo: &Memo) -> MemoKind {
+    if memo == &Memo::default() {
+        MemoKind::Empty
+    } else {
+        match memo.to_utf8() {
+            Some(Ok(_)) => MemoKind::Text,
+            _ => MemoKind::Binary,
+        }
+    }
+}
+
+/// Returns the decoded memo for a received note, if it is known.
 ///
-/// The note is identified by its row index in the `received_notes` table within the data
-/// database.
+/// The note is identified by its row index in the `received_notes` table within the
+/// data database. Unlike [`get_received_memo_as_utf8`], this returns the full `Memo`
+/// regardless of whether its contents are valid UTF-8, so callers can inspect the
+/// leading type byte or relay the raw bytes elsewhere.
 ///
 /// # Examples
 ///
@@ -206,17 +413,17 @@ pub fn get_verified_balance(
 /// use zcash_client_sqlite::{
 ///     NoteId,
 ///     WalletDB,
-///     wallet::get_received_memo_as_utf8,
+///     wallet::get_received_memo,
 /// };
 ///
 /// let data_file = NamedTempFile::new().unwrap();
 /// let db = WalletDB::for_path(data_file).unwrap();
-/// let memo = get_received_memo_as_utf8(&db, NoteId(27));
+/// let memo = get_received_memo(&db, NoteId(27));
 /// ```
-pub fn get_received_memo_as_utf8(
+pub fn get_received_memo(
     data: &WalletDB,
     id_note: NoteId,
-) -> Result<Option<String>, SqliteClientError> {
+) -> Result<Option<Memo>, SqliteClientError> {
     let memo: Vec<_> = data.0.query_row(
         "SELECT memo FROM received_notes
         WHERE id_note = ?",
@@ -224,7 +431,33 @@ pub fn get_received_memo_as_utf8(
         |row| row.get(0),
     )?;
 
-    match Memo::from_bytes(&memo) {
+    Ok(Memo::from_bytes(&memo))
+}
+
+/// Returns the memo for a received note, if it is known and a valid UTF-8 string.
+///
+/// The note is identified by its row index in the `received_notes` table within the data
+/// database.
+///
+/// # Examples
+///
+/// ```
+/// use tempfile::NamedTempFile;
+/// use zcash_client_sqlite::{
+///     NoteId,
+///     WalletDB,
+///     wallet::get_received_memo_as_utf8,
+/// };
+///
+/// let data_file = NamedTempFile::new().unwrap();
+/// let db = WalletDB::for_path(data_file).unwrap();
+/// let memo = get_received_memo_as_utf8(&db, NoteId(27));
+/// ```
+pub fn get_received_memo_as_utf8(
+    data: &WalletDB,
+    id_note: NoteId,
+) -> Result<Option<String>, SqliteClientError> {
+    match get_received_memo(data, id_note)? {
         Some(memo) => match memo.to_utf8() {
             Some(Ok(res)) => Ok(Some(res)),
             Some(Err(e)) => Err(SqliteClientError(Error::InvalidMemo(e))),
@@ -234,6 +467,37 @@ pub fn get_received_memo_as_utf8(
     }
 }
 
+/// Returns the decoded memo for a sent note, if it is known.
+///
+/// The note is identified by its row index in the `sent_notes` table within the data
+/// database. Unlike [`get_sent_memo_as_utf8`], this returns the full `Memo` regardless
+/// of whether its contents are valid UTF-8.
+///
+/// # Examples
+///
+/// ```
+/// use tempfile::NamedTempFile;
+/// use zcash_client_sqlite::{
+///     NoteId,
+///     WalletDB,
+///     wallet::get_sent_memo,
+/// };
+///
+/// let data_file = NamedTempFile::new().unwrap();
+/// let db = WalletDB::for_path(data_file).unwrap();
+/// let memo = get_sent_memo(&db, NoteId(12));
+/// ```
+pub fn get_sent_memo(data: &WalletDB, id_note: NoteId) -> Result<Option<Memo>, SqliteClientError> {
+    let memo: Vec<_> = data.0.query_row(
+        "SELECT memo FROM sent_notes
+        WHERE id_note = ?",
+        &[id_note.0],
+        |row| row.get(0),
+    )?;
+
+    Ok(Memo::from_bytes(&memo))
+}
+
 /// Returns the memo for a sent note, if it is known and a valid UTF-8 string.
 ///
 /// The note is identified by its row index in the `sent_notes` table within the data
@@ -257,14 +521,7 @@ pub fn get_sent_memo_as_utf8(
     data: &WalletDB,
     id_note: NoteId,
 ) -> Result<Option<String>, SqliteClientError> {
-    let memo: Vec<_> = data.0.query_row(
-        "SELECT memo FROM sent_notes
-        WHERE id_note = ?",
-        &[id_note.0],
-        |row| row.get(0),
-    )?;
-
-    match Memo::from_bytes(&memo) {
+    match get_sent_memo(data, id_note)? {
         Some(memo) => match memo.to_utf8() {
             Some(Ok(res)) => Ok(Some(res)),
             Some(Err(e)) => Err(SqliteClientError(Error::InvalidMemo(e))),
@@ -419,6 +676,123 @@ pub fn get_witnesses(
     Ok(res)
 }
 
+/// A single entry in a wallet's transaction history, as returned by
+/// [`get_transactions`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxHistoryEntry {
+    /// The txid of the transaction.
+    pub txid: TxId,
+    /// The height at which the transaction was mined, or `None` if it has not yet been
+    /// mined.
+    pub block_height: Option<BlockHeight>,
+    /// The position of the transaction within its block, or `None` if it has not yet
+    /// been mined.
+    pub tx_index: Option<i64>,
+    /// The net change in the account's balance caused by this transaction: the sum of
+    /// the values of notes received by the account, minus the sum of the values of
+    /// notes sent from the account, within this transaction.
+    pub account_balance_delta: Amount,
+    /// The number of blocks, including the one the transaction was mined in, that have
+    /// been added to the chain since this transaction was mined. `None` for
+    /// transactions that have not yet been mined.
+    pub confirmations: Option<u32>,
+}
+
+/// Computes the confirmation count for a transaction mined at `block_height`, relative
+/// to `current_tip_u32`.
+///
+/// Saturates rather than trusting the caller-supplied tip to always dominate the
+/// stored height: a stale tip, a reorg in progress, or simple caller error could
+/// otherwise underflow this subtraction.
+fn confirmations_at(block_height: BlockHeight, current_tip_u32: u32) -> u32 {
+    current_tip_u32.saturating_sub(u32::from(block_height)) + 1
+}
+
+/// Returns a page of the transaction history for the specified account. Mined
+/// transactions are ordered from most to least recent; unmined transactions, if
+/// included, come after all mined ones.
+///
+/// `min_confirmations` filters out transactions with fewer than that many confirmations
+/// relative to `current_tip`; pass `0` to include unmined transactions as well.
+/// `limit` and `offset` allow the caller to page through the history without loading
+/// every row into memory at once.
+pub fn get_transactions(
+    data: &WalletDB,
+    account: AccountId,
+    current_tip: BlockHeight,
+    min_confirmations: u32,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<TxHistoryEntry>, SqliteClientError> {
+    let mut stmt = data.0.prepare(
+        "SELECT transactions.txid, transactions.block, transactions.tx_index,
+                COALESCE(received.total, 0) - COALESCE(sent.total, 0) AS net_value
+         FROM transactions
+         LEFT JOIN (
+             SELECT tx, SUM(value) AS total FROM received_notes
+             WHERE account = ?
+             GROUP BY tx
+         ) AS received ON received.tx = transactions.id_tx
+         LEFT JOIN (
+             SELECT tx, SUM(value) AS total FROM sent_notes
+             WHERE from_account = ?
+             GROUP BY tx
+         ) AS sent ON sent.tx = transactions.id_tx
+         WHERE (received.total IS NOT NULL OR sent.total IS NOT NULL)
+           AND (? = 0 OR (transactions.block IS NOT NULL
+                          AND ? - transactions.block + 1 >= ?))
+         ORDER BY transactions.block DESC, transactions.tx_index DESC
+         LIMIT ? OFFSET ?",
+    )?;
+
+    let current_tip_u32 = u32::from(current_tip);
+    let rows = stmt.query_map(
+        &[
+            account.0.to_sql()?,
+            account.0.to_sql()?,
+            min_confirmations.to_sql()?,
+            current_tip_u32.to_sql()?,
+            min_confirmations.to_sql()?,
+            limit.to_sql()?,
+            offset.to_sql()?,
+        ],
+        |row| {
+            let txid_bytes: Vec<u8> = row.get(0)?;
+            let block_height: Option<u32> = row.get(1)?;
+            let tx_index: Option<i64> = row.get(2)?;
+            let net_value: i64 = row.get(3)?;
+            Ok((txid_bytes, block_height, tx_index, net_value))
+        },
+    )?;
+
+    let mut res = vec![];
+    for row in rows {
+        let (txid_bytes, block_height, tx_index, net_value) = row?;
+
+        let mut txid = [0u8; 32];
+        txid.copy_from_slice(&txid_bytes);
+
+        let block_height = block_height.map(BlockHeight::from);
+        let confirmations = block_height.map(|h| confirmations_at(h, current_tip_u32));
+
+        let account_balance_delta = Amount::from_i64(net_value).map_err(|_| {
+            SqliteClientError(Error::CorruptedData(
+                "Net value of transaction is out of range".to_string(),
+            ))
+        })?;
+
+        res.push(TxHistoryEntry {
+            txid: TxId(txid),
+            block_height,
+            tx_index,
+            account_balance_delta,
+            confirmations,
+        });
+    }
+
+    Ok(res)
+}
+
 pub fn get_nullifiers(data: &WalletDB) -> Result<Vec<(Nullifier, AccountId)>, SqliteClientError> {
     // Get the nullifiers for the notes we are tracking
     let mut stmt_fetch_nullifiers = data
@@ -441,9 +815,12 @@ pub fn get_nullifiers(data: &WalletDB) -> Result<Vec<(Nullifier, AccountId)>, Sq
 #[cfg(test)]
 mod tests {
     use rusqlite::Connection;
+    use std::num::NonZeroU32;
     use tempfile::NamedTempFile;
 
     use zcash_primitives::{
+        consensus::BlockHeight,
+        note_encryption::Memo,
         transaction::components::Amount,
         zip32::{ExtendedFullViewingKey, ExtendedSpendingKey},
     };
@@ -456,7 +833,87 @@ mod tests {
         AccountId, WalletDB,
     };
 
-    use super::{get_address, get_balance};
+    use super::{
+        classify_memo, confirmations_at, get_address, get_addresses, get_balance,
+        get_next_available_address, MemoKind,
+    };
+
+    #[test]
+    fn confirmations_at_saturates_against_a_stale_tip() {
+        // Ordinary case: five blocks, including the mined block itself, have been
+        // added since the transaction was mined.
+        assert_eq!(confirmations_at(BlockHeight::from(95), 99), 5);
+
+        // A regression test for a stale/reorging tip below the transaction's recorded
+        // height: this must saturate to a single confirmation rather than panicking on
+        // underflow or wrapping around to a huge count.
+        assert_eq!(confirmations_at(BlockHeight::from(100), 50), 1);
+    }
+
+    #[test]
+    fn classify_memo_distinguishes_empty_text_and_binary() {
+        assert_eq!(classify_memo(&Memo::default()), MemoKind::Empty);
+
+        let text_memo = Memo::from_bytes(b"hello").unwrap();
+        assert_eq!(classify_memo(&text_memo), MemoKind::Text);
+
+        let binary_memo = Memo::from_bytes(&[0x80, 0x80, 0x80]).unwrap();
+        assert_eq!(classify_memo(&binary_memo), MemoKind::Binary);
+    }
+
+    #[test]
+    fn get_next_available_address_round_trips_through_get_addresses() {
+        let data_file = NamedTempFile::new().unwrap();
+        let db_data = WalletDB(Connection::open(data_file.path()).unwrap());
+        init_data_database(&db_data).unwrap();
+
+        let extsk = ExtendedSpendingKey::master(&[]);
+        let extfvks = [ExtendedFullViewingKey::from(&extsk)];
+        init_accounts_table(&db_data, &tests::network(), &extfvks).unwrap();
+
+        let addr_0 = get_next_available_address(&db_data, &tests::network(), AccountId(0))
+            .unwrap()
+            .unwrap();
+        let addr_1 = get_next_available_address(&db_data, &tests::network(), AccountId(0))
+            .unwrap()
+            .unwrap();
+        assert_ne!(addr_0, addr_1);
+
+        let addrs = get_addresses(&db_data, &tests::network(), AccountId(0)).unwrap();
+        assert_eq!(addrs.len(), 2);
+        assert!(addrs[0].0 < addrs[1].0);
+        assert_eq!(addrs[0].1, addr_0);
+        assert_eq!(addrs[1].1, addr_1);
+
+        // An account with no stored addresses yet reports none, rather than erroring.
+        assert_eq!(
+            get_addresses(&db_data, &tests::network(), AccountId(1)).unwrap(),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn get_next_available_address_reports_diversifier_exhaustion() {
+        let data_file = NamedTempFile::new().unwrap();
+        let db_data = WalletDB(Connection::open(data_file.path()).unwrap());
+        init_data_database(&db_data).unwrap();
+
+        let extsk = ExtendedSpendingKey::master(&[]);
+        let extfvks = [ExtendedFullViewingKey::from(&extsk)];
+        init_accounts_table(&db_data, &tests::network(), &extfvks).unwrap();
+
+        super::ensure_addresses_table(&db_data).unwrap();
+        db_data
+            .0
+            .execute(
+                "INSERT INTO addresses (account, diversifier_index_be, address)
+                VALUES (0, ?, 'placeholder')",
+                &[vec![0xffu8; 11]],
+            )
+            .unwrap();
+
+        assert!(get_next_available_address(&db_data, &tests::network(), AccountId(0)).is_err());
+    }
 
     #[test]
     fn empty_database_has_no_balance() {
@@ -473,7 +930,12 @@ mod tests {
         assert_eq!(get_balance(&db_data, AccountId(0)).unwrap(), Amount::zero());
 
         // We can't get an anchor height, as we have not scanned any blocks.
-        assert_eq!((&db_data).get_target_and_anchor_heights().unwrap(), None);
+        assert_eq!(
+            (&db_data)
+                .get_target_and_anchor_heights(NonZeroU32::new(10).unwrap())
+                .unwrap(),
+            None
+        );
 
         // An invalid account has zero balance
         assert!(get_address(&db_data, &tests::network(), AccountId(1)).is_err());